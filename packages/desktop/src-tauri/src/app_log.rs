@@ -0,0 +1,84 @@
+//! Application log
+//!
+//! Records the app's own diagnostics (connection lifecycle, failed sends and
+//! emits) to a daily-rotated file under `~/.abbacchio`, so problems in the
+//! field can be investigated after the fact.
+
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+const LOG_FILE_PREFIX: &str = "app";
+const LOG_FILE_SUFFIX: &str = "log";
+/// Number of daily log files kept before the oldest is deleted
+const MAX_LOG_FILES: usize = 7;
+
+/// Directory holding the app log files
+fn log_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .home_dir()
+        .map(|home| home.join(".abbacchio"))
+        .map_err(|e| format!("Could not find home directory: {}", e))
+}
+
+/// Install the global tracing subscriber writing to the rolling app log
+pub fn init(app: &AppHandle) -> Result<(), String> {
+    let dir = log_dir(app)?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir)
+        .map_err(|e| e.to_string())?;
+
+    tracing_subscriber::fmt()
+        .with_writer(appender)
+        .with_ansi(false)
+        .with_max_level(tracing::Level::INFO)
+        .try_init()
+        .map_err(|e| e.to_string())
+}
+
+/// Find the most recent log file (file names embed the date, so they sort)
+fn current_log_file(app: &AppHandle) -> Result<Option<PathBuf>, String> {
+    let dir = log_dir(app)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let prefix = format!("{}.", LOG_FILE_PREFIX);
+    let suffix = format!(".{}", LOG_FILE_SUFFIX);
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(&suffix))
+        })
+        .max())
+}
+
+/// Get the path of the current app log file, if one has been written
+#[tauri::command]
+pub async fn get_app_log_path(app: AppHandle) -> Result<Option<String>, String> {
+    Ok(current_log_file(&app)?.map(|path| path.to_string_lossy().into_owned()))
+}
+
+/// Get the last `lines` lines of the current app log file
+#[tauri::command]
+pub async fn tail_app_log(app: AppHandle, lines: usize) -> Result<Vec<String>, String> {
+    let Some(path) = current_log_file(&app)? else {
+        return Ok(Vec::new());
+    };
+
+    let contents = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let all: Vec<&str> = contents.lines().collect();
+    let start = all.len().saturating_sub(lines);
+    Ok(all[start..].iter().map(|line| line.to_string()).collect())
+}
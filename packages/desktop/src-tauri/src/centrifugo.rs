@@ -3,13 +3,59 @@
 //! This module handles the WebSocket connection to Centrifugo server,
 //! maintaining it in the background regardless of webview state.
 
-use futures_util::{SinkExt, StreamExt};
+use futures_util::stream::SplitSink;
+use futures_util::{Sink, SinkExt, StreamExt};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tauri::{AppHandle, Emitter, Manager, State};
-use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+use tokio::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::error::{CapacityError, Error as WsError};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::{
+    connect_async_with_config, tungstenite::Message, MaybeTlsStream, WebSocketStream,
+};
+use tracing::{error, info, warn};
+
+/// Default size above which a message is skipped instead of delivered
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 8 << 20;
+/// Default hard limit enforced by the WebSocket transport
+const DEFAULT_MAX_FRAME_SIZE: usize = 64 << 20;
+/// Default Centrifugo namespace prefix for log channels
+const DEFAULT_CHANNEL_PREFIX: &str = "logs:";
+/// How many times a panicked connection task is restarted before giving up
+const MAX_PANIC_RESTARTS: u32 = 3;
+/// Pause before restarting a panicked connection task
+const PANIC_RESTART_DELAY: Duration = Duration::from_secs(1);
+/// A connection task that ran this long before panicking gets its restart budget back
+const PANIC_RESTART_RESET_AFTER: Duration = Duration::from_secs(60);
+/// First reconnect delay, doubled on every failed attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Upper bound for the reconnect delay
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Default seconds between client pings
+const DEFAULT_PING_INTERVAL_SECS: u64 = 25;
+/// Longest accepted ping interval, so the timeout math can't overflow
+const MAX_PING_INTERVAL_SECS: u64 = 3600;
+/// How long the token command may run before it is killed
+const TOKEN_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// `CREATE_NO_WINDOW` process creation flag
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+/// How often suppressed-message counts are reported for live filters
+const LIVE_FILTER_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// A subscribe without a reply after this long is considered stuck
+const STALE_SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// A publish without a reply after this long is failed
+const PUBLISH_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Connection state shared across the application
 pub struct ConnectionState {
@@ -19,6 +65,11 @@ pub struct ConnectionState {
     status: RwLock<ConnectionStatus>,
     /// Subscribed channels (channel_id -> channel_name)
     subscriptions: RwLock<HashMap<String, String>>,
+    /// Live message filters (channel_id -> compiled regex)
+    live_filters: RwLock<HashMap<String, Regex>>,
+    /// Id of the latest connection. Connecting or disconnecting bumps it, so
+    /// a loop whose id no longer matches knows it has been superseded.
+    generation: AtomicU64,
 }
 
 impl Default for ConnectionState {
@@ -27,6 +78,53 @@ impl Default for ConnectionState {
             command_tx: Mutex::new(None),
             status: RwLock::new(ConnectionStatus::Disconnected),
             subscriptions: RwLock::new(HashMap::new()),
+            live_filters: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ConnectionState {
+    /// Look up the channel name of a subscribed channel
+    pub async fn channel_name(&self, channel_id: &str) -> Option<String> {
+        self.subscriptions.read().await.get(channel_id).cloned()
+    }
+
+    /// Supersede the running connection loop, if any, and return the new id
+    fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+/// Receives the events of a connection loop; the app forwards them to the frontend
+type EventSink = Arc<dyn Fn(CentrifugoEvent) + Send + Sync>;
+
+/// One connection started by `connect_centrifugo`. Its loop keeps retrying
+/// while it is current and never touches the shared status or emits events
+/// once a later connect or a disconnect has superseded it.
+#[derive(Clone)]
+struct Connection {
+    events: EventSink,
+    state: Arc<ConnectionState>,
+    generation: u64,
+}
+
+impl Connection {
+    fn is_current(&self) -> bool {
+        self.state.generation.load(Ordering::SeqCst) == self.generation
+    }
+
+    async fn set_status(&self, status: ConnectionStatus) {
+        // Checked under the lock, so a newer connection's status is never overwritten
+        let mut current = self.state.status.write().await;
+        if self.is_current() {
+            *current = status;
+        }
+    }
+
+    fn emit(&self, event: CentrifugoEvent) {
+        if self.is_current() {
+            (self.events)(event);
         }
     }
 }
@@ -40,6 +138,53 @@ pub enum ConnectionStatus {
     Error(String),
 }
 
+/// Optional settings for a Centrifugo connection
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConnectOptions {
+    /// Get the token by running the `tokenCommand` from the local desktop
+    /// config instead of using the token passed to `connect_centrifugo`. The
+    /// command is run before every connect and again before the token expires.
+    pub use_token_command: bool,
+    /// Messages larger than this many bytes are skipped and reported with an
+    /// `oversized-message` event instead of being delivered. Defaults to 8 MiB.
+    pub max_message_size: Option<usize>,
+    /// Hard limit enforced by the WebSocket transport. A frame above it cannot
+    /// be skipped and closes the connection. Defaults to 64 MiB.
+    pub max_frame_size: Option<usize>,
+    /// Namespace prefix prepended to channel names when talking to Centrifugo.
+    /// A missing `:` separator is added. Defaults to `logs:`.
+    pub channel_prefix: Option<String>,
+    /// Seconds between client pings. A connection that receives nothing for
+    /// twice this long is treated as dead and reconnected. Defaults to 25,
+    /// clamped to 1..=3600.
+    pub ping_interval: Option<u64>,
+    /// Give up after this many failed reconnect attempts in a row, with a
+    /// `connection-abandoned` event. `None` retries forever.
+    pub max_reconnect_attempts: Option<u32>,
+}
+
+/// Settings file under `~/.abbacchio` that only the Rust side reads
+const DESKTOP_CONFIG_FILE: &str = "desktop-config.json";
+
+/// Local desktop settings. These can run programs, so they are read from disk
+/// rather than accepted from the webview.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct DesktopConfig {
+    /// Program followed by its arguments that prints a fresh token on stdout
+    token_command: Option<Vec<String>>,
+}
+
+/// Where a connection gets its token from
+#[derive(Debug, Clone)]
+enum TokenSource {
+    /// Token passed to `connect_centrifugo`
+    Static(String),
+    /// Command from the desktop config, run before every connect and refresh
+    Command(Vec<String>),
+}
+
 /// Commands sent to the WebSocket task
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -47,19 +192,96 @@ enum CentrifugoCommand {
     Connect { url: String, token: String },
     Subscribe { channel_id: String, channel_name: String },
     Unsubscribe { channel_id: String },
+    ListStale { reply: oneshot::Sender<Vec<StaleSubscription>> },
+    ClearStale { retry: bool, reply: oneshot::Sender<Vec<StaleSubscription>> },
+    Publish {
+        channel: String,
+        data: serde_json::Value,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
     Disconnect,
 }
 
+/// A subscribe request awaiting its reply
+struct PendingSubscribe {
+    channel_id: String,
+    channel_name: String,
+    sent_at: Instant,
+}
+
+/// A publish request awaiting its reply
+struct PendingPublish {
+    reply: oneshot::Sender<Result<(), String>>,
+    sent_at: Instant,
+}
+
+/// Request id reserved for the connect command
+const CONNECT_REQUEST_ID: u32 = 1;
+/// First id handed out to other requests, and where ids wrap around to
+const FIRST_REQUEST_ID: u32 = 2;
+
+/// Allocates request ids for a connection. Ids wrap around from `u32::MAX`
+/// back to `FIRST_REQUEST_ID`, never reuse the reserved connect id, and skip
+/// ids still awaiting a reply so replies can't be matched to the wrong request.
+struct RequestIds {
+    next: u32,
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        Self {
+            next: FIRST_REQUEST_ID,
+        }
+    }
+}
+
+impl RequestIds {
+    fn next_request_id(
+        &mut self,
+        pending_subscribes: &HashMap<u32, PendingSubscribe>,
+        pending_publishes: &HashMap<u32, PendingPublish>,
+        pending_refresh: Option<u32>,
+    ) -> u32 {
+        loop {
+            let id = self.next;
+            self.next = if id == u32::MAX {
+                FIRST_REQUEST_ID
+            } else {
+                id + 1
+            };
+            if !pending_subscribes.contains_key(&id)
+                && !pending_publishes.contains_key(&id)
+                && pending_refresh != Some(id)
+            {
+                return id;
+            }
+        }
+    }
+}
+
+/// A subscription that was rejected or never got a reply
+#[derive(Debug, Clone, Serialize)]
+pub struct StaleSubscription {
+    pub channel_id: String,
+    pub channel_name: String,
+    /// Error returned by the server, or `None` if the subscribe is stuck pending
+    pub error: Option<String>,
+}
+
 /// Events emitted to the frontend
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum CentrifugoEvent {
     Connected,
     Disconnected { reason: String },
+    Reconnecting { attempt: u32 },
+    ConnectionAbandoned { attempts: u32 },
     Error { error: String },
     Subscribed { channel_id: String },
     SubscriptionError { channel_id: String, error: String },
     Publication { channel_id: String, data: serde_json::Value },
+    OversizedMessage { channel: Option<String>, size: usize },
+    LiveFilterSuppressed { channel_id: String, count: u64 },
 }
 
 /// Centrifugo protocol messages
@@ -74,8 +296,10 @@ struct CentrifugoRequest {
 #[serde(tag = "method", content = "params", rename_all = "lowercase")]
 enum CentrifugoMethod {
     Connect { token: String },
+    Refresh { token: String },
     Subscribe { channel: String },
     Unsubscribe { channel: String },
+    Publish { channel: String, data: serde_json::Value },
 }
 
 #[derive(Debug, Deserialize)]
@@ -93,12 +317,27 @@ struct CentrifugoError {
     message: String,
 }
 
+/// Token expiration details returned in connect and refresh replies
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CentrifugoExpiry {
+    expires: bool,
+    ttl: u64,
+}
+
 #[derive(Debug, Deserialize)]
 struct CentrifugoPush {
     channel: Option<String>,
     pub r#pub: Option<CentrifugoPublication>,
 }
 
+/// Only the channel of a push, used to report oversized messages without
+/// materializing their payload
+#[derive(Debug, Deserialize)]
+struct CentrifugoPushChannel {
+    channel: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct CentrifugoPublication {
     data: serde_json::Value,
@@ -120,9 +359,24 @@ pub async fn connect_centrifugo(
     state: State<'_, Arc<ConnectionState>>,
     url: String,
     token: String,
+    options: Option<ConnectOptions>,
 ) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    let token = if options.use_token_command {
+        TokenSource::Command(load_token_command(&app)?)
+    } else {
+        TokenSource::Static(token)
+    };
+
     // Create command channel
-    let (tx, mut rx) = mpsc::channel::<CentrifugoCommand>(32);
+    let (tx, rx) = mpsc::channel::<CentrifugoCommand>(32);
+    let generation = state.next_generation();
+
+    let conn = Connection {
+        events: Arc::new(move |event| emit_event(&app, event)),
+        state: state.inner().clone(),
+        generation,
+    };
 
     // Store the sender
     {
@@ -131,195 +385,1048 @@ pub async fn connect_centrifugo(
     }
 
     // Update status
-    {
-        let mut status = state.status.write().await;
-        *status = ConnectionStatus::Connecting;
-    }
-
-    let state_clone = state.inner().clone();
-    let app_clone = app.clone();
+    conn.set_status(ConnectionStatus::Connecting).await;
 
-    // Spawn WebSocket task
+    // Spawn WebSocket task under a supervisor
     tauri::async_runtime::spawn(async move {
-        run_websocket_loop(app_clone, state_clone, url, token, &mut rx).await;
+        supervise_websocket_loop(conn, url, token, options, rx).await;
     });
 
     Ok(())
 }
 
-async fn run_websocket_loop(
-    app: AppHandle,
-    state: Arc<ConnectionState>,
+/// Run the WebSocket loop in its own task and restart it if it panics.
+async fn supervise_websocket_loop(
+    conn: Connection,
     url: String,
-    token: String,
-    rx: &mut mpsc::Receiver<CentrifugoCommand>,
+    token: TokenSource,
+    options: ConnectOptions,
+    rx: mpsc::Receiver<CentrifugoCommand>,
 ) {
-    // Connect to WebSocket
-    let ws_stream = match connect_async(&url).await {
-        Ok((stream, _)) => stream,
-        Err(e) => {
-            {
-                let mut status = state.status.write().await;
-                *status = ConnectionStatus::Error(e.to_string());
+    let mut rx = Some(rx);
+    let mut restarts = 0;
+
+    loop {
+        // A panicked task takes its receiver with it, so restarts need a new channel
+        let mut task_rx = match rx.take() {
+            Some(rx) => rx,
+            None => {
+                let (tx, rx) = mpsc::channel::<CentrifugoCommand>(32);
+                let mut command_tx = conn.state.command_tx.lock().await;
+                // A newer connect installs its own sender; leave it alone
+                if !conn.is_current() {
+                    return;
+                }
+                *command_tx = Some(tx);
+                rx
             }
-            let _ = app.emit("centrifugo-event", CentrifugoEvent::Error {
-                error: format!("Connection failed: {}", e),
-            });
+        };
+
+        let started_at = Instant::now();
+        let task = tokio::spawn({
+            let conn = conn.clone();
+            let url = url.clone();
+            let token = token.clone();
+            let options = options.clone();
+            async move {
+                run_websocket_loop(conn, url, token, options, &mut task_rx).await;
+            }
+        });
+
+        let panic = match task.await {
+            Ok(()) => return,
+            Err(e) if e.is_panic() => e.into_panic(),
+            Err(e) => {
+                warn!(error = %e, "Connection task cancelled");
+                return;
+            }
+        };
+
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let error = format!("Connection task panicked: {}", message);
+        error!(%error, restarts, "Connection task panicked");
+        if !conn.is_current() {
             return;
         }
-    };
+        conn.set_status(ConnectionStatus::Error(error.clone()))
+            .await;
+        conn.emit(CentrifugoEvent::Error { error });
 
-    let (mut write, mut read) = ws_stream.split();
+        if started_at.elapsed() >= PANIC_RESTART_RESET_AFTER {
+            restarts = 0;
+        }
+        if restarts >= MAX_PANIC_RESTARTS {
+            return;
+        }
+        restarts += 1;
+        tokio::time::sleep(PANIC_RESTART_DELAY).await;
+        conn.set_status(ConnectionStatus::Connecting).await;
+    }
+}
 
-    // Send connect request
-    let connect_req = CentrifugoRequest {
-        id: 1,
-        method: CentrifugoMethod::Connect { token },
-    };
+/// Result of handling an incoming frame
+#[derive(Debug, PartialEq)]
+enum FrameOutcome {
+    /// Keep reading from the current connection
+    Continue,
+    /// The server rejected the connect; start over with a new connection
+    Reconnect,
+}
 
-    if let Err(e) = write
-        .send(Message::Text(serde_json::to_string(&connect_req).unwrap().into()))
-        .await
+/// Result of handling a command from the app
+#[derive(Debug, PartialEq)]
+enum CommandOutcome {
+    Continue,
+    /// The connection was superseded; close it and stop the loop
+    Stop,
+}
+
+/// Write half of a Centrifugo WebSocket connection
+type WsWrite = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// Bookkeeping of a connection loop. Apart from the pending publishes and
+/// the token refresh, it outlives a single WebSocket session so
+/// subscriptions can be restored after a reconnect.
+struct ConnectionLoop {
+    conn: Connection,
+    token: TokenSource,
+    max_message_size: usize,
+    channel_prefix: String,
+    request_ids: RequestIds,
+    pending_subscribes: HashMap<u32, PendingSubscribe>,
+    /// Publish requests awaiting their reply; failed when the connection drops
+    pending_publishes: HashMap<u32, PendingPublish>,
+    pending_refresh: Option<u32>,
+    refresh_at: Option<Instant>,
+    /// Subscriptions the server rejected (channel_id -> stale entry)
+    failed_subscribes: HashMap<String, StaleSubscription>,
+    /// Channel name (without prefix) -> channel_id, used to route publications
+    channel_to_id: HashMap<String, String>,
+    /// Subscribe commands received while waiting to reconnect (channel_id, channel_name)
+    queued_subscribes: Vec<(String, String)>,
+    /// Log entries dropped by live filters since the last report (channel_id -> count)
+    suppressed: HashMap<String, u64>,
+    /// Reconnect attempts since the last successful connect
+    attempt: u32,
+}
+
+impl ConnectionLoop {
+    fn new(conn: Connection, token: TokenSource, options: &ConnectOptions) -> Self {
+        Self {
+            conn,
+            token,
+            max_message_size: options.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE),
+            channel_prefix: normalize_channel_prefix(
+                options
+                    .channel_prefix
+                    .as_deref()
+                    .unwrap_or(DEFAULT_CHANNEL_PREFIX),
+            ),
+            request_ids: RequestIds::default(),
+            pending_subscribes: HashMap::new(),
+            pending_publishes: HashMap::new(),
+            pending_refresh: None,
+            refresh_at: None,
+            failed_subscribes: HashMap::new(),
+            channel_to_id: HashMap::new(),
+            queued_subscribes: Vec::new(),
+            suppressed: HashMap::new(),
+            attempt: 0,
+        }
+    }
+
+    /// Allocate an id that no request awaiting a reply is using
+    fn next_request_id(&mut self) -> u32 {
+        self.request_ids.next_request_id(
+            &self.pending_subscribes,
+            &self.pending_publishes,
+            self.pending_refresh,
+        )
+    }
+
+    /// Queue a subscribe for the next connection, at most once per channel
+    fn queue_subscribe(&mut self, channel_id: String, channel_name: String) {
+        if !self
+            .queued_subscribes
+            .iter()
+            .any(|(id, _)| *id == channel_id)
+        {
+            self.queued_subscribes.push((channel_id, channel_name));
+        }
+    }
+
+    /// Send a subscribe for a channel and track it until its reply arrives
+    async fn send_subscribe<S>(&mut self, write: &mut S, channel_id: String, channel_name: String)
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
     {
-        let _ = app.emit("centrifugo-event", CentrifugoEvent::Error {
-            error: format!("Failed to send connect: {}", e),
-        });
-        return;
+        let id = self.next_request_id();
+        let req = CentrifugoRequest {
+            id,
+            method: CentrifugoMethod::Subscribe {
+                channel: prefixed_channel(&self.channel_prefix, &channel_name),
+            },
+        };
+        if let Err(e) = send_request(write, &req).await {
+            warn!(%channel_id, error = %e, "Failed to send subscribe request");
+        }
+        self.pending_subscribes.insert(
+            id,
+            PendingSubscribe {
+                channel_id,
+                channel_name,
+                sent_at: Instant::now(),
+            },
+        );
     }
 
-    let mut request_id = 2u32;
-    let mut pending_subscribes: HashMap<u32, (String, String)> = HashMap::new();
-    let mut channel_to_id: HashMap<String, String> = HashMap::new();
+    /// Subscribe on the open connection, or queue it while reconnecting
+    async fn subscribe<S>(
+        &mut self,
+        write: Option<&mut S>,
+        channel_id: String,
+        channel_name: String,
+    ) where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
+    {
+        match write {
+            Some(write) => self.send_subscribe(write, channel_id, channel_name).await,
+            None => self.queue_subscribe(channel_id, channel_name),
+        }
+    }
 
-    loop {
-        tokio::select! {
-            // Handle incoming WebSocket messages
-            msg = read.next() => {
-                match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        if let Ok(response) = serde_json::from_str::<CentrifugoResponse>(&text) {
-                            // Handle response
-                            if let Some(id) = response.id {
-                                if id == 1 {
-                                    // Connect response
-                                    if response.error.is_some() {
-                                        let err = response.error.unwrap();
-                                        {
-                                            let mut status = state.status.write().await;
-                                            *status = ConnectionStatus::Error(err.message.clone());
-                                        }
-                                        let _ = app.emit("centrifugo-event", CentrifugoEvent::Error {
-                                            error: err.message,
-                                        });
-                                        return;
-                                    }
-                                    // Connected successfully
-                                    {
-                                        let mut status = state.status.write().await;
-                                        *status = ConnectionStatus::Connected;
-                                    }
-                                    let _ = app.emit("centrifugo-event", CentrifugoEvent::Connected);
-                                } else if let Some((channel_id, channel_name)) = pending_subscribes.remove(&id) {
-                                    // Subscribe response
-                                    if let Some(err) = response.error {
-                                        let _ = app.emit("centrifugo-event", CentrifugoEvent::SubscriptionError {
-                                            channel_id,
-                                            error: err.message,
-                                        });
-                                    } else {
-                                        channel_to_id.insert(format!("logs:{}", channel_name), channel_id.clone());
-                                        {
-                                            let mut subs = state.subscriptions.write().await;
-                                            subs.insert(channel_id.clone(), channel_name);
-                                        }
-                                        let _ = app.emit("centrifugo-event", CentrifugoEvent::Subscribed { channel_id });
-                                    }
-                                }
+    /// Settle requests that will never get a reply once the connection drops:
+    /// in-flight subscribes are retried on the next connection, publishes fail.
+    fn connection_lost(&mut self) {
+        let mut in_flight: Vec<PendingSubscribe> = self
+            .pending_subscribes
+            .drain()
+            .map(|(_, pending)| pending)
+            .collect();
+        in_flight.sort_by_key(|pending| pending.sent_at);
+        for PendingSubscribe {
+            channel_id,
+            channel_name,
+            ..
+        } in in_flight
+        {
+            self.queue_subscribe(channel_id, channel_name);
+        }
+        for (_, pending) in self.pending_publishes.drain() {
+            let _ = pending.reply.send(Err(
+                "Connection lost before the publish was confirmed".to_string()
+            ));
+        }
+    }
+
+    /// When the oldest publish awaiting its reply times out
+    fn publish_deadline(&self) -> Option<Instant> {
+        self.pending_publishes
+            .values()
+            .map(|pending| pending.sent_at + PUBLISH_REPLY_TIMEOUT)
+            .min()
+    }
+
+    /// Fail publishes that have waited too long for their reply
+    fn expire_publishes(&mut self) {
+        let expired: Vec<u32> = self
+            .pending_publishes
+            .iter()
+            .filter(|(_, pending)| pending.sent_at.elapsed() >= PUBLISH_REPLY_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(pending) = self.pending_publishes.remove(&id) {
+                warn!(id, "Publish reply timed out");
+                let _ = pending
+                    .reply
+                    .send(Err("Timed out waiting for the publish reply".to_string()));
+            }
+        }
+    }
+
+    /// Handle a command from the app. `write` is the open connection, or
+    /// `None` while waiting to reconnect.
+    async fn handle_command<S>(
+        &mut self,
+        write: Option<&mut S>,
+        cmd: Option<CentrifugoCommand>,
+    ) -> CommandOutcome
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
+    {
+        let state = self.conn.state.clone();
+        match cmd {
+            Some(CentrifugoCommand::Subscribe {
+                channel_id,
+                channel_name,
+            }) => {
+                // Already pending or queued: the reply will confirm it
+                if self
+                    .pending_subscribes
+                    .values()
+                    .any(|pending| pending.channel_id == channel_id)
+                    || self
+                        .queued_subscribes
+                        .iter()
+                        .any(|(id, _)| *id == channel_id)
+                {
+                    return CommandOutcome::Continue;
+                }
+                // Subscribed on the open connection: confirm again instead of
+                // sending a duplicate. A rejected channel is retried, and while
+                // reconnecting the subscribe is queued so its reply confirms it.
+                let failed = self.failed_subscribes.remove(&channel_id).is_some();
+                if !failed
+                    && write.is_some()
+                    && state.subscriptions.read().await.contains_key(&channel_id)
+                {
+                    self.conn.emit(CentrifugoEvent::Subscribed { channel_id });
+                    return CommandOutcome::Continue;
+                }
+                self.subscribe(write, channel_id, channel_name).await;
+            }
+            Some(CentrifugoCommand::Unsubscribe { channel_id }) => {
+                self.queued_subscribes.retain(|(id, _)| *id != channel_id);
+                // Forget a subscribe still awaiting its reply, so the reply is
+                // ignored; the unsubscribe below undoes it on the server
+                let mut pending_name = None;
+                self.pending_subscribes.retain(|_, pending| {
+                    if pending.channel_id != channel_id {
+                        return true;
+                    }
+                    pending_name = Some(pending.channel_name.clone());
+                    false
+                });
+                let subscribed_name = state.subscriptions.write().await.remove(&channel_id);
+                if let Some(channel_name) = &subscribed_name {
+                    self.channel_to_id.remove(channel_name);
+                }
+                if let (Some(write), Some(channel_name)) = (write, subscribed_name.or(pending_name))
+                {
+                    let req = CentrifugoRequest {
+                        id: self.next_request_id(),
+                        method: CentrifugoMethod::Unsubscribe {
+                            channel: prefixed_channel(&self.channel_prefix, &channel_name),
+                        },
+                    };
+                    if let Err(e) = send_request(write, &req).await {
+                        warn!(%channel_id, error = %e, "Failed to send unsubscribe request");
+                    }
+                }
+                state.live_filters.write().await.remove(&channel_id);
+                self.suppressed.remove(&channel_id);
+                self.failed_subscribes.remove(&channel_id);
+            }
+            Some(CentrifugoCommand::ListStale { reply }) => {
+                let _ = reply.send(stale_subscriptions(
+                    &self.pending_subscribes,
+                    &self.failed_subscribes,
+                ));
+            }
+            Some(CentrifugoCommand::ClearStale { retry, reply }) => {
+                let stale = stale_subscriptions(&self.pending_subscribes, &self.failed_subscribes);
+                self.failed_subscribes.clear();
+                self.pending_subscribes
+                    .retain(|_, pending| pending.sent_at.elapsed() < STALE_SUBSCRIBE_TIMEOUT);
+                {
+                    let mut subs = state.subscriptions.write().await;
+                    for entry in &stale {
+                        subs.remove(&entry.channel_id);
+                        self.channel_to_id.remove(&entry.channel_name);
+                    }
+                }
+                if retry {
+                    match write {
+                        Some(write) => {
+                            for entry in &stale {
+                                self.send_subscribe(
+                                    write,
+                                    entry.channel_id.clone(),
+                                    entry.channel_name.clone(),
+                                )
+                                .await;
                             }
-                        } else if let Ok(push) = serde_json::from_str::<CentrifugoPush>(&text) {
-                            // Handle push (publication)
-                            if let (Some(channel), Some(publication)) = (push.channel, push.r#pub) {
-                                if let Some(channel_id) = channel_to_id.get(&channel) {
-                                    let _ = app.emit("centrifugo-event", CentrifugoEvent::Publication {
-                                        channel_id: channel_id.clone(),
-                                        data: publication.data,
-                                    });
-                                }
+                        }
+                        None => {
+                            for entry in &stale {
+                                self.queue_subscribe(
+                                    entry.channel_id.clone(),
+                                    entry.channel_name.clone(),
+                                );
                             }
                         }
                     }
-                    Some(Ok(Message::Close(_))) | None => {
-                        {
-                            let mut status = state.status.write().await;
-                            *status = ConnectionStatus::Disconnected;
-                        }
-                        let _ = app.emit("centrifugo-event", CentrifugoEvent::Disconnected {
-                            reason: "Connection closed".to_string(),
-                        });
-                        return;
+                }
+                let _ = reply.send(stale);
+            }
+            Some(CentrifugoCommand::Publish {
+                channel,
+                data,
+                reply,
+            }) => {
+                let Some(write) = write else {
+                    let _ = reply.send(Err("Not connected".to_string()));
+                    return CommandOutcome::Continue;
+                };
+                let id = self.next_request_id();
+                let req = CentrifugoRequest {
+                    id,
+                    method: CentrifugoMethod::Publish {
+                        channel: prefixed_channel(&self.channel_prefix, &channel),
+                        data,
+                    },
+                };
+                match send_request(write, &req).await {
+                    Ok(()) => {
+                        self.pending_publishes.insert(
+                            id,
+                            PendingPublish {
+                                reply,
+                                sent_at: Instant::now(),
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        warn!(error = %e, "Failed to send publish request");
+                        let _ = reply.send(Err(e));
                     }
-                    Some(Err(e)) => {
-                        {
-                            let mut status = state.status.write().await;
-                            *status = ConnectionStatus::Error(e.to_string());
+                }
+            }
+            Some(CentrifugoCommand::Disconnect) | None => {
+                // Superseded by disconnect_centrifugo or a newer connect,
+                // which own the status from here on
+                info!("Connection superseded, stopping");
+                return CommandOutcome::Stop;
+            }
+            Some(CentrifugoCommand::Connect { .. }) => {
+                // Already connected or connecting, ignore
+            }
+        }
+        CommandOutcome::Continue
+    }
+
+    /// Handle a text frame. One frame can carry several newline-delimited
+    /// replies and pushes, each handled on its own.
+    async fn handle_text<S>(&mut self, write: &mut S, text: &str) -> FrameOutcome
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
+    {
+        for line in text.split('\n') {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if self.handle_line(write, line).await == FrameOutcome::Reconnect {
+                return FrameOutcome::Reconnect;
+            }
+        }
+        FrameOutcome::Continue
+    }
+
+    async fn handle_line<S>(&mut self, write: &mut S, line: &str) -> FrameOutcome
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
+    {
+        // Server ping: answer with a pong
+        if line == "{}" {
+            if let Err(e) = write.send(Message::Text("{}".into())).await {
+                warn!(error = %e, "Failed to send pong");
+            }
+            return FrameOutcome::Continue;
+        }
+        if line.len() > self.max_message_size {
+            let channel = serde_json::from_str::<CentrifugoPushChannel>(line)
+                .ok()
+                .and_then(|push| push.channel);
+            warn!(?channel, size = line.len(), "Skipping oversized message");
+            self.conn.emit(CentrifugoEvent::OversizedMessage {
+                channel,
+                size: line.len(),
+            });
+            return FrameOutcome::Continue;
+        }
+        // Every object parses as a response, so only a reply id tells them apart
+        match serde_json::from_str::<CentrifugoResponse>(line) {
+            Ok(response) if response.id.is_some() => self.handle_reply(write, response).await,
+            _ => {
+                if let Ok(push) = serde_json::from_str::<CentrifugoPush>(line) {
+                    self.handle_push(push).await;
+                }
+                FrameOutcome::Continue
+            }
+        }
+    }
+
+    /// Match a reply to the request it answers
+    async fn handle_reply<S>(&mut self, write: &mut S, response: CentrifugoResponse) -> FrameOutcome
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: Display,
+    {
+        let Some(id) = response.id else {
+            return FrameOutcome::Continue;
+        };
+        let state = self.conn.state.clone();
+        if id == CONNECT_REQUEST_ID {
+            // Connect response
+            if let Some(err) = response.error {
+                error!(code = err.code, error = %err.message, "Connect rejected");
+                self.conn
+                    .set_status(ConnectionStatus::Error(err.message.clone()))
+                    .await;
+                self.conn
+                    .emit(CentrifugoEvent::Error { error: err.message });
+                return FrameOutcome::Reconnect;
+            }
+            // Connected successfully
+            self.conn.set_status(ConnectionStatus::Connected).await;
+            if matches!(self.token, TokenSource::Command(_)) {
+                self.refresh_at = refresh_deadline(response.result.as_ref());
+            }
+            self.attempt = 0;
+            info!("Connected");
+            self.conn.emit(CentrifugoEvent::Connected);
+
+            // Restore subscriptions from the previous connection;
+            // the replies repopulate channel_to_id and emit Subscribed
+            let active: Vec<(String, String)> = state
+                .subscriptions
+                .read()
+                .await
+                .iter()
+                .map(|(id, name)| (id.clone(), name.clone()))
+                .collect();
+            for (channel_id, channel_name) in active {
+                if self
+                    .pending_subscribes
+                    .values()
+                    .any(|pending| pending.channel_id == channel_id)
+                {
+                    continue;
+                }
+                self.send_subscribe(write, channel_id, channel_name).await;
+            }
+        } else if self.pending_refresh == Some(id) {
+            // Refresh response
+            self.pending_refresh = None;
+            if let Some(err) = response.error {
+                warn!(code = err.code, error = %err.message, "Token refresh rejected");
+                self.conn.emit(CentrifugoEvent::Error {
+                    error: format!("Token refresh failed: {}", err.message),
+                });
+            } else {
+                self.refresh_at = refresh_deadline(response.result.as_ref());
+            }
+        } else if let Some(pending) = self.pending_subscribes.remove(&id) {
+            // Subscribe response
+            let PendingSubscribe {
+                channel_id,
+                channel_name,
+                ..
+            } = pending;
+            if let Some(err) = response.error {
+                warn!(%channel_id, %channel_name, error = %err.message, "Subscribe rejected");
+                // A rejected resubscribe is no longer live; track it only as failed
+                state.subscriptions.write().await.remove(&channel_id);
+                self.channel_to_id.remove(&channel_name);
+                self.failed_subscribes.insert(
+                    channel_id.clone(),
+                    StaleSubscription {
+                        channel_id: channel_id.clone(),
+                        channel_name,
+                        error: Some(err.message.clone()),
+                    },
+                );
+                self.conn.emit(CentrifugoEvent::SubscriptionError {
+                    channel_id,
+                    error: err.message,
+                });
+            } else {
+                info!(%channel_id, %channel_name, "Subscribed");
+                self.failed_subscribes.remove(&channel_id);
+                self.channel_to_id
+                    .insert(channel_name.clone(), channel_id.clone());
+                {
+                    let mut subs = state.subscriptions.write().await;
+                    subs.insert(channel_id.clone(), channel_name);
+                }
+                self.conn.emit(CentrifugoEvent::Subscribed { channel_id });
+            }
+        } else if let Some(pending) = self.pending_publishes.remove(&id) {
+            // Publish response
+            let result = match response.error {
+                Some(err) => Err(err.message),
+                None => Ok(()),
+            };
+            let _ = pending.reply.send(result);
+        }
+        FrameOutcome::Continue
+    }
+
+    /// Route a publication to its channel, applying the live filter
+    async fn handle_push(&mut self, push: CentrifugoPush) {
+        let (Some(channel), Some(publication)) = (push.channel, push.r#pub) else {
+            return;
+        };
+        let Some(channel_id) = strip_channel_prefix(&self.channel_prefix, &channel)
+            .and_then(|name| self.channel_to_id.get(name))
+        else {
+            return;
+        };
+        let data = match self.conn.state.live_filters.read().await.get(channel_id) {
+            Some(filter) => {
+                let (kept, dropped) = apply_live_filter(filter, publication.data);
+                if dropped > 0 {
+                    *self.suppressed.entry(channel_id.clone()).or_default() += dropped;
+                }
+                kept
+            }
+            None => Some(publication.data),
+        };
+        if let Some(data) = data {
+            self.conn.emit(CentrifugoEvent::Publication {
+                channel_id: channel_id.clone(),
+                data,
+            });
+        }
+    }
+}
+
+async fn run_websocket_loop(
+    conn: Connection,
+    url: String,
+    token: TokenSource,
+    options: ConnectOptions,
+    rx: &mut mpsc::Receiver<CentrifugoCommand>,
+) {
+    let mut lp = ConnectionLoop::new(conn.clone(), token, &options);
+    let max_frame_size = options
+        .max_frame_size
+        .unwrap_or(DEFAULT_MAX_FRAME_SIZE)
+        .max(lp.max_message_size);
+    let ws_config = WebSocketConfig::default()
+        .max_message_size(Some(max_frame_size))
+        .max_frame_size(Some(max_frame_size));
+    let ping_interval = Duration::from_secs(
+        options
+            .ping_interval
+            .unwrap_or(DEFAULT_PING_INTERVAL_SECS)
+            .clamp(1, MAX_PING_INTERVAL_SECS),
+    );
+    let mut suppressed_report = tokio::time::interval(LIVE_FILTER_REPORT_INTERVAL);
+    let mut first_attempt = true;
+
+    'reconnect: loop {
+        if !first_attempt {
+            if !conn.is_current() {
+                return;
+            }
+            lp.connection_lost();
+            if options
+                .max_reconnect_attempts
+                .is_some_and(|max| lp.attempt >= max)
+            {
+                warn!(attempts = lp.attempt, "Giving up reconnecting");
+                conn.set_status(ConnectionStatus::Error("connection abandoned".to_string()))
+                    .await;
+                conn.emit(CentrifugoEvent::ConnectionAbandoned {
+                    attempts: lp.attempt,
+                });
+                return;
+            }
+            lp.attempt += 1;
+            let attempt = lp.attempt;
+            let delay = reconnect_delay(attempt);
+            conn.set_status(ConnectionStatus::Connecting).await;
+            info!(attempt, delay_ms = delay.as_millis() as u64, "Reconnecting");
+            conn.emit(CentrifugoEvent::Reconnecting { attempt });
+
+            // Wait out the backoff, still answering commands
+            let deadline = Instant::now() + delay;
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    cmd = rx.recv() => {
+                        if lp.handle_command(None::<&mut WsWrite>, cmd).await == CommandOutcome::Stop {
+                            return;
                         }
-                        let _ = app.emit("centrifugo-event", CentrifugoEvent::Error {
-                            error: e.to_string(),
-                        });
-                        return;
                     }
-                    _ => {}
                 }
             }
+        }
+        first_attempt = false;
 
-            // Handle commands from the app
-            cmd = rx.recv() => {
-                match cmd {
-                    Some(CentrifugoCommand::Subscribe { channel_id, channel_name }) => {
-                        let req = CentrifugoRequest {
-                            id: request_id,
-                            method: CentrifugoMethod::Subscribe {
-                                channel: format!("logs:{}", channel_name),
-                            },
-                        };
-                        pending_subscribes.insert(request_id, (channel_id, channel_name));
-                        request_id += 1;
-                        let _ = write.send(Message::Text(serde_json::to_string(&req).unwrap().into())).await;
+        // Resolve a fresh token from the token command, if configured
+        let connect_token = match &lp.token {
+            TokenSource::Command(command) => match fetch_token(command).await {
+                Ok(token) => token,
+                Err(e) => {
+                    error!(error = %e, "Failed to obtain token");
+                    conn.set_status(ConnectionStatus::Error(e.clone())).await;
+                    conn.emit(CentrifugoEvent::Error { error: e });
+                    continue 'reconnect;
+                }
+            },
+            TokenSource::Static(token) => token.clone(),
+        };
+
+        // Connect to WebSocket
+        let ws_stream = match connect_async_with_config(&url, Some(ws_config), false).await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                error!(%url, error = %e, "Connection failed");
+                conn.set_status(ConnectionStatus::Error(e.to_string()))
+                    .await;
+                conn.emit(CentrifugoEvent::Error {
+                    error: format!("Connection failed: {}", e),
+                });
+                continue 'reconnect;
+            }
+        };
+
+        let (mut write, mut read) = ws_stream.split();
+        lp.pending_refresh = None;
+        lp.refresh_at = None;
+
+        // Send connect request
+        let connect_req = CentrifugoRequest {
+            id: CONNECT_REQUEST_ID,
+            method: CentrifugoMethod::Connect {
+                token: connect_token,
+            },
+        };
+
+        if let Err(e) = send_request(&mut write, &connect_req).await {
+            error!(error = %e, "Failed to send connect request");
+            conn.emit(CentrifugoEvent::Error {
+                error: format!("Failed to send connect: {}", e),
+            });
+            continue 'reconnect;
+        }
+
+        // Subscribe to channels requested while disconnected
+        for (channel_id, channel_name) in std::mem::take(&mut lp.queued_subscribes) {
+            lp.send_subscribe(&mut write, channel_id, channel_name)
+                .await;
+        }
+
+        let mut last_frame = Instant::now();
+        let mut ping = tokio::time::interval_at(last_frame + ping_interval, ping_interval);
+
+        loop {
+            tokio::select! {
+                // Handle incoming WebSocket messages
+                msg = read.next() => {
+                    if let Some(Ok(_)) = &msg {
+                        last_frame = Instant::now();
+                    }
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            let outcome = lp.handle_text(&mut write, &text).await;
+                            if outcome == FrameOutcome::Reconnect {
+                                continue 'reconnect;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            warn!("Connection closed by server");
+                            conn.set_status(ConnectionStatus::Disconnected).await;
+                            conn.emit(CentrifugoEvent::Disconnected {
+                                reason: "Connection closed".to_string(),
+                            });
+                            continue 'reconnect;
+                        }
+                        Some(Err(e)) => {
+                            error!(error = %e, "WebSocket error");
+                            if let WsError::Capacity(CapacityError::MessageTooLong { size, .. }) = &e {
+                                conn.emit(CentrifugoEvent::OversizedMessage {
+                                    channel: None,
+                                    size: *size,
+                                });
+                            }
+                            conn.set_status(ConnectionStatus::Error(e.to_string())).await;
+                            conn.emit(CentrifugoEvent::Error {
+                                error: e.to_string(),
+                            });
+                            continue 'reconnect;
+                        }
+                        _ => {}
                     }
-                    Some(CentrifugoCommand::Unsubscribe { channel_id }) => {
-                        let subs = state.subscriptions.read().await;
-                        if let Some(channel_name) = subs.get(&channel_id) {
+                }
+
+                // Refresh the token before the server expires the connection
+                _ = wait_until(lp.refresh_at) => {
+                    lp.refresh_at = None;
+                    let TokenSource::Command(command) = &lp.token else {
+                        continue;
+                    };
+                    match fetch_token(command).await {
+                        Ok(token) => {
+                            let id = lp.next_request_id();
                             let req = CentrifugoRequest {
-                                id: request_id,
-                                method: CentrifugoMethod::Unsubscribe {
-                                    channel: format!("logs:{}", channel_name),
-                                },
+                                id,
+                                method: CentrifugoMethod::Refresh { token },
                             };
-                            request_id += 1;
-                            let _ = write.send(Message::Text(serde_json::to_string(&req).unwrap().into())).await;
-                            channel_to_id.remove(&format!("logs:{}", channel_name));
+                            lp.pending_refresh = Some(id);
+                            if let Err(e) = send_request(&mut write, &req).await {
+                                warn!(error = %e, "Failed to send refresh request");
+                            }
                         }
-                        drop(subs);
-                        let mut subs = state.subscriptions.write().await;
-                        subs.remove(&channel_id);
-                    }
-                    Some(CentrifugoCommand::Disconnect) | None => {
-                        let _ = write.close().await;
-                        {
-                            let mut status = state.status.write().await;
-                            *status = ConnectionStatus::Disconnected;
+                        Err(e) => {
+                            warn!(error = %e, "Failed to obtain refreshed token");
+                            conn.emit(CentrifugoEvent::Error { error: e });
                         }
-                        let _ = app.emit("centrifugo-event", CentrifugoEvent::Disconnected {
-                            reason: "User disconnected".to_string(),
+                    }
+                }
+
+                // Fail publishes the server never answered
+                _ = wait_until(lp.publish_deadline()) => lp.expire_publishes(),
+
+                // Keep the connection alive and notice when it has silently died
+                _ = ping.tick() => {
+                    if last_frame.elapsed() >= ping_interval * 2 {
+                        warn!(idle_secs = last_frame.elapsed().as_secs(), "Connection timed out");
+                        conn.set_status(ConnectionStatus::Disconnected).await;
+                        conn.emit(CentrifugoEvent::Disconnected {
+                            reason: "Connection timed out".to_string(),
                         });
-                        return;
+                        continue 'reconnect;
                     }
-                    Some(CentrifugoCommand::Connect { .. }) => {
-                        // Already connected, ignore
+                    if let Err(e) = write.send(Message::Text("{}".into())).await {
+                        warn!(error = %e, "Failed to send ping");
+                    }
+                }
+
+                // Report how much the live filters dropped
+                _ = suppressed_report.tick() => {
+                    for (channel_id, count) in lp.suppressed.drain() {
+                        conn.emit(CentrifugoEvent::LiveFilterSuppressed { channel_id, count });
                     }
                 }
+
+                // Handle commands from the app
+                cmd = rx.recv() => {
+                    if lp.handle_command(Some(&mut write), cmd).await == CommandOutcome::Stop {
+                        if let Err(e) = write.close().await {
+                            warn!(error = %e, "Failed to close WebSocket cleanly");
+                        }
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Serialize a request and send it as a text frame
+async fn send_request<S>(write: &mut S, req: &CentrifugoRequest) -> Result<(), String>
+where
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    let json =
+        serde_json::to_string(req).map_err(|e| format!("Failed to encode request: {}", e))?;
+    write
+        .send(Message::Text(json.into()))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Emit an event to the frontend, logging (rather than dropping) failures
+pub(crate) fn emit_event(app: &AppHandle, event: CentrifugoEvent) {
+    if let Err(e) = app.emit("centrifugo-event", event) {
+        warn!(error = %e, "Failed to emit centrifugo-event");
+    }
+}
+
+/// Delay before reconnect attempt `attempt` (starting at 1): exponential
+/// backoff from 500ms capped at 30s, minus up to half of it as random jitter
+fn reconnect_delay(attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let delay = RECONNECT_BASE_DELAY
+        .saturating_mul(1 << exponent)
+        .min(RECONNECT_MAX_DELAY);
+    let max_jitter_ms = delay.as_millis() as u64 / 2;
+    let jitter_ms = RandomState::new().build_hasher().finish() % (max_jitter_ms + 1);
+    delay - Duration::from_millis(jitter_ms)
+}
+
+/// Collect rejected subscriptions and subscribes stuck without a reply
+fn stale_subscriptions(
+    pending: &HashMap<u32, PendingSubscribe>,
+    failed: &HashMap<String, StaleSubscription>,
+) -> Vec<StaleSubscription> {
+    let stuck = pending
+        .values()
+        .filter(|pending| pending.sent_at.elapsed() >= STALE_SUBSCRIBE_TIMEOUT)
+        .map(|pending| StaleSubscription {
+            channel_id: pending.channel_id.clone(),
+            channel_name: pending.channel_name.clone(),
+            error: None,
+        });
+    failed.values().cloned().chain(stuck).collect()
+}
+
+/// Whether a log entry passes a live filter. Encrypted entries can't be
+/// matched here, so they are always let through.
+fn entry_matches(filter: &Regex, entry: &serde_json::Value) -> bool {
+    if entry
+        .get("encrypted")
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false)
+    {
+        return true;
+    }
+    entry
+        .get("msg")
+        .and_then(serde_json::Value::as_str)
+        .is_some_and(|msg| filter.is_match(msg))
+}
+
+/// Filter the log entries of a publication payload (`log` or `batch`).
+/// Returns what is left to emit, if anything, and how many entries were dropped.
+fn apply_live_filter(
+    filter: &Regex,
+    mut data: serde_json::Value,
+) -> (Option<serde_json::Value>, u64) {
+    match data.get("type").and_then(serde_json::Value::as_str) {
+        Some("log") => {
+            if data
+                .get("data")
+                .is_some_and(|entry| entry_matches(filter, entry))
+            {
+                (Some(data), 0)
+            } else {
+                (None, 1)
             }
         }
+        Some("batch") => {
+            let Some(entries) = data
+                .get_mut("data")
+                .and_then(serde_json::Value::as_array_mut)
+            else {
+                return (Some(data), 0);
+            };
+            let before = entries.len();
+            entries.retain(|entry| entry_matches(filter, entry));
+            let dropped = (before - entries.len()) as u64;
+            if entries.is_empty() {
+                (None, dropped)
+            } else {
+                (Some(data), dropped)
+            }
+        }
+        _ => (Some(data), 0),
+    }
+}
+
+/// Ensure a non-empty channel prefix ends with the `:` namespace separator
+fn normalize_channel_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim();
+    if prefix.is_empty() || prefix.ends_with(':') {
+        prefix.to_string()
+    } else {
+        format!("{}:", prefix)
+    }
+}
+
+/// Build the Centrifugo channel for a channel name
+fn prefixed_channel(prefix: &str, channel_name: &str) -> String {
+    format!("{}{}", prefix, channel_name)
+}
+
+/// Recover the channel name from a Centrifugo channel, if it has the prefix
+fn strip_channel_prefix<'a>(prefix: &str, channel: &'a str) -> Option<&'a str> {
+    channel.strip_prefix(prefix)
+}
+
+/// Read the token command from `~/.abbacchio/desktop-config.json`
+fn load_token_command(app: &AppHandle) -> Result<Vec<String>, String> {
+    let path = app
+        .path()
+        .home_dir()
+        .map_err(|e| format!("Could not find home directory: {}", e))?
+        .join(".abbacchio")
+        .join(DESKTOP_CONFIG_FILE);
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let config: DesktopConfig = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    config
+        .token_command
+        .filter(|command| !command.is_empty())
+        .ok_or_else(|| format!("No tokenCommand configured in {}", path.display()))
+}
+
+/// Run the configured token command and return the token it prints. A
+/// command that hasn't finished within `TOKEN_COMMAND_TIMEOUT` is killed.
+async fn fetch_token(command: &[String]) -> Result<String, String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "Token command is empty".to_string())?;
+
+    let mut process = tokio::process::Command::new(program);
+    process.args(args).stdin(Stdio::null()).kill_on_drop(true);
+    // Don't flash a console window from the GUI app
+    #[cfg(windows)]
+    process.creation_flags(CREATE_NO_WINDOW);
+
+    let output = tokio::time::timeout(TOKEN_COMMAND_TIMEOUT, process.output())
+        .await
+        .map_err(|_| {
+            format!(
+                "Token command `{}` timed out after {}s",
+                program,
+                TOKEN_COMMAND_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| format!("Failed to run token command `{}`: {}", program, e))?;
+
+    if !output.status.success() {
+        let code = output
+            .status
+            .code()
+            .map_or_else(|| "unknown".to_string(), |code| code.to_string());
+        return Err(format!(
+            "Token command `{}` failed with exit code {}: {}",
+            program,
+            code,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if token.is_empty() {
+        return Err(format!("Token command `{}` did not print a token", program));
+    }
+    Ok(token)
+}
+
+/// Compute when the token must be refreshed from a connect/refresh result.
+/// A ttl too far out to represent never needs a refresh.
+fn refresh_deadline(result: Option<&serde_json::Value>) -> Option<Instant> {
+    let expiry = result
+        .and_then(|result| serde_json::from_value::<CentrifugoExpiry>(result.clone()).ok())?;
+    if !expiry.expires {
+        return None;
+    }
+    Instant::now().checked_add(Duration::from_secs(expiry.ttl))
+}
+
+/// Sleep until the deadline, or forever if there is none
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
     }
 }
 
@@ -330,6 +1437,11 @@ pub async fn subscribe_channel(
     channel_id: String,
     channel_name: String,
 ) -> Result<(), String> {
+    let channel_name = channel_name.trim().to_string();
+    if channel_name.is_empty() {
+        return Err("Channel name cannot be empty".to_string());
+    }
+
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
         tx.send(CentrifugoCommand::Subscribe { channel_id, channel_name })
@@ -361,13 +1473,113 @@ pub async fn unsubscribe_channel(
 /// Disconnect from Centrifugo
 #[tauri::command]
 pub async fn disconnect_centrifugo(
+    app: AppHandle,
     state: State<'_, Arc<ConnectionState>>,
 ) -> Result<(), String> {
+    // Supersede the running loop first so it stops retrying and leaves the
+    // status alone, then tell it to close its socket
+    let tx = {
+        let mut status = state.status.write().await;
+        state.next_generation();
+        *status = ConnectionStatus::Disconnected;
+        state.command_tx.lock().await.take()
+    };
+    info!("Disconnecting on request");
+    emit_event(
+        &app,
+        CentrifugoEvent::Disconnected {
+            reason: "User disconnected".to_string(),
+        },
+    );
+    if let Some(tx) = tx {
+        // The loop may already have stopped on its own
+        let _ = tx.send(CentrifugoCommand::Disconnect).await;
+    }
+    Ok(())
+}
+
+/// List subscriptions that were rejected or are stuck waiting for a reply
+#[tauri::command]
+pub async fn list_stale_subscriptions(
+    state: State<'_, Arc<ConnectionState>>,
+) -> Result<Vec<StaleSubscription>, String> {
+    let (reply, response) = oneshot::channel();
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
-        tx.send(CentrifugoCommand::Disconnect)
+        tx.send(CentrifugoCommand::ListStale { reply })
             .await
             .map_err(|e| e.to_string())?;
+    } else {
+        return Err("Not connected".to_string());
+    }
+    drop(tx);
+    response.await.map_err(|e| e.to_string())
+}
+
+/// Drop stale subscriptions from the connection's bookkeeping, optionally
+/// sending a fresh subscribe for each. Returns the subscriptions cleared.
+#[tauri::command]
+pub async fn clear_stale_subscriptions(
+    state: State<'_, Arc<ConnectionState>>,
+    retry: bool,
+) -> Result<Vec<StaleSubscription>, String> {
+    let (reply, response) = oneshot::channel();
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(CentrifugoCommand::ClearStale { retry, reply })
+            .await
+            .map_err(|e| e.to_string())?;
+    } else {
+        return Err("Not connected".to_string());
+    }
+    drop(tx);
+    response.await.map_err(|e| e.to_string())
+}
+
+/// Publish `data` to a channel (name without the channel prefix), returning
+/// the server's error if the publish is rejected
+#[tauri::command]
+pub async fn publish_centrifugo(
+    state: State<'_, Arc<ConnectionState>>,
+    channel: String,
+    data: serde_json::Value,
+) -> Result<(), String> {
+    let (reply, response) = oneshot::channel();
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(CentrifugoCommand::Publish {
+            channel,
+            data,
+            reply,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    } else {
+        return Err("Not connected".to_string());
+    }
+    drop(tx);
+    response
+        .await
+        .map_err(|_| "Connection lost before the publish was confirmed".to_string())?
+}
+
+/// Only emit live publications of a channel whose message matches `regex`.
+/// Passing `None` clears the filter.
+#[tauri::command]
+pub async fn set_live_filter(
+    state: State<'_, Arc<ConnectionState>>,
+    channel_id: String,
+    regex: Option<String>,
+) -> Result<(), String> {
+    let mut filters = state.live_filters.write().await;
+    match regex {
+        Some(pattern) => {
+            let filter = Regex::new(&pattern).map_err(|e| format!("Invalid regex: {}", e))?;
+            filters.insert(channel_id, filter);
+        }
+        None => {
+            filters.remove(&channel_id);
+        }
     }
     Ok(())
 }
@@ -380,3 +1592,485 @@ pub async fn get_connection_status(
     let status = state.status.read().await;
     Ok(status.clone())
 }
+
+/// Get the active subscriptions as (channel_id, channel_name) pairs
+#[tauri::command]
+pub async fn get_subscriptions(
+    state: State<'_, Arc<ConnectionState>>,
+) -> Result<Vec<(String, String)>, String> {
+    let subs = state.subscriptions.read().await;
+    Ok(subs
+        .iter()
+        .map(|(channel_id, channel_name)| (channel_id.clone(), channel_name.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::sync::Mutex as StdMutex;
+    use std::task::{Context, Poll};
+    use tokio::net::TcpListener;
+
+    /// Collects the messages a loop sends instead of writing them to a socket
+    #[derive(Default)]
+    struct Sent(Vec<Message>);
+
+    impl Sink<Message> for Sent {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, message: Message) -> Result<(), Infallible> {
+            self.0.push(message);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A current connection whose events are collected instead of emitted
+    fn test_connection() -> (Connection, Arc<StdMutex<Vec<CentrifugoEvent>>>) {
+        let events = Arc::new(StdMutex::new(Vec::new()));
+        let sink = events.clone();
+        let state = Arc::new(ConnectionState::default());
+        let generation = state.next_generation();
+        let conn = Connection {
+            events: Arc::new(move |event| sink.lock().unwrap().push(event)),
+            state,
+            generation,
+        };
+        (conn, events)
+    }
+
+    fn test_loop(conn: &Connection) -> ConnectionLoop {
+        ConnectionLoop::new(
+            conn.clone(),
+            TokenSource::Static("token".to_string()),
+            &ConnectOptions::default(),
+        )
+    }
+
+    fn pending(channel_id: &str, channel_name: &str) -> PendingSubscribe {
+        PendingSubscribe {
+            channel_id: channel_id.to_string(),
+            channel_name: channel_name.to_string(),
+            sent_at: Instant::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn handles_every_reply_in_a_batched_frame() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        lp.pending_subscribes.insert(2, pending("a", "api"));
+        lp.pending_subscribes.insert(3, pending("w", "worker"));
+
+        let mut sent = Sent::default();
+        let frame = "{\"id\":2,\"result\":{}}\n{\"id\":3,\"result\":{}}\n";
+        assert_eq!(
+            lp.handle_text(&mut sent, frame).await,
+            FrameOutcome::Continue
+        );
+
+        assert!(lp.pending_subscribes.is_empty());
+        assert_eq!(conn.state.channel_name("a").await.as_deref(), Some("api"));
+        assert_eq!(
+            conn.state.channel_name("w").await.as_deref(),
+            Some("worker")
+        );
+        let subscribed: Vec<String> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                CentrifugoEvent::Subscribed { channel_id } => Some(channel_id.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(subscribed, ["a", "w"]);
+    }
+
+    #[tokio::test]
+    async fn routes_a_push_batched_after_a_reply() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        lp.pending_subscribes.insert(2, pending("a", "api"));
+
+        let mut sent = Sent::default();
+        let frame = concat!(
+            r#"{"id":2,"result":{}}"#,
+            "\n",
+            r#"{"channel":"logs:api","pub":{"data":{"type":"log","data":{"msg":"hi"}}}}"#,
+        );
+        assert_eq!(
+            lp.handle_text(&mut sent, frame).await,
+            FrameOutcome::Continue
+        );
+
+        let events = events.lock().unwrap();
+        assert!(
+            matches!(&events[0], CentrifugoEvent::Subscribed { channel_id } if channel_id == "a")
+        );
+        assert!(matches!(
+            &events[1],
+            CentrifugoEvent::Publication { channel_id, data } if channel_id == "a" && data["data"]["msg"] == "hi"
+        ));
+    }
+
+    #[tokio::test]
+    async fn drops_a_channel_whose_resubscribe_is_rejected() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        conn.state
+            .subscriptions
+            .write()
+            .await
+            .insert("a".to_string(), "api".to_string());
+        lp.channel_to_id.insert("api".to_string(), "a".to_string());
+
+        let mut sent = Sent::default();
+        let frame = concat!(
+            r#"{"id":1,"result":{}}"#,
+            "\n",
+            r#"{"id":2,"error":{"code":103,"message":"permission denied"}}"#,
+        );
+        assert_eq!(
+            lp.handle_text(&mut sent, frame).await,
+            FrameOutcome::Continue
+        );
+
+        let resubscribe: serde_json::Value =
+            serde_json::from_str(sent.0[0].to_text().unwrap()).unwrap();
+        assert_eq!(resubscribe["params"]["channel"], "logs:api");
+        assert!(conn.state.subscriptions.read().await.is_empty());
+        assert!(lp.channel_to_id.is_empty());
+        assert!(lp.failed_subscribes.contains_key("a"));
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [
+                CentrifugoEvent::Connected,
+                CentrifugoEvent::SubscriptionError { channel_id, .. },
+            ] if channel_id == "a"
+        ));
+    }
+
+    fn subscribe_command(channel_id: &str, channel_name: &str) -> Option<CentrifugoCommand> {
+        Some(CentrifugoCommand::Subscribe {
+            channel_id: channel_id.to_string(),
+            channel_name: channel_name.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn retries_a_rejected_channel_instead_of_confirming_it() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        let mut sent = Sent::default();
+        lp.send_subscribe(&mut sent, "a".to_string(), "api".to_string())
+            .await;
+        let frame = r#"{"id":2,"error":{"code":103,"message":"permission denied"}}"#;
+        lp.handle_text(&mut sent, frame).await;
+        events.lock().unwrap().clear();
+
+        lp.handle_command(Some(&mut sent), subscribe_command("a", "api"))
+            .await;
+
+        assert_eq!(sent.0.len(), 2);
+        assert_eq!(lp.pending_subscribes.len(), 1);
+        assert!(lp.failed_subscribes.is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn queues_a_subscribed_channel_while_reconnecting() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        conn.state
+            .subscriptions
+            .write()
+            .await
+            .insert("a".to_string(), "api".to_string());
+
+        lp.handle_command(None::<&mut Sent>, subscribe_command("a", "api"))
+            .await;
+
+        assert_eq!(lp.queued_subscribes, [("a".to_string(), "api".to_string())]);
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    fn pending_publish(
+        sent_at: Instant,
+    ) -> (PendingPublish, oneshot::Receiver<Result<(), String>>) {
+        let (reply, response) = oneshot::channel();
+        (PendingPublish { reply, sent_at }, response)
+    }
+
+    #[tokio::test]
+    async fn settles_in_flight_requests_when_the_connection_drops() {
+        let (conn, _) = test_connection();
+        let mut lp = test_loop(&conn);
+        lp.pending_subscribes.insert(2, pending("a", "api"));
+        let (publish, mut response) = pending_publish(Instant::now());
+        lp.pending_publishes.insert(3, publish);
+
+        lp.connection_lost();
+
+        assert!(lp.pending_subscribes.is_empty());
+        assert_eq!(lp.queued_subscribes, [("a".to_string(), "api".to_string())]);
+        assert!(lp.pending_publishes.is_empty());
+        assert_eq!(
+            response.try_recv().unwrap(),
+            Err("Connection lost before the publish was confirmed".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out_publishes_without_a_reply() {
+        let (conn, _) = test_connection();
+        let mut lp = test_loop(&conn);
+        let now = Instant::now();
+        let (old, mut old_response) = pending_publish(now - PUBLISH_REPLY_TIMEOUT);
+        let (fresh, mut fresh_response) = pending_publish(now);
+        lp.pending_publishes.insert(2, old);
+        lp.pending_publishes.insert(3, fresh);
+        assert_eq!(lp.publish_deadline(), Some(now));
+
+        lp.expire_publishes();
+
+        assert!(old_response.try_recv().unwrap().is_err());
+        assert!(fresh_response.try_recv().is_err());
+        assert_eq!(lp.pending_publishes.len(), 1);
+        assert_eq!(lp.publish_deadline(), Some(now + PUBLISH_REPLY_TIMEOUT));
+    }
+
+    #[tokio::test]
+    async fn unsubscribing_cancels_a_pending_subscribe() {
+        let (conn, events) = test_connection();
+        let mut lp = test_loop(&conn);
+        let mut sent = Sent::default();
+        lp.handle_command(Some(&mut sent), subscribe_command("a", "api"))
+            .await;
+        let unsubscribe = Some(CentrifugoCommand::Unsubscribe {
+            channel_id: "a".to_string(),
+        });
+        lp.handle_command(Some(&mut sent), unsubscribe).await;
+
+        assert!(lp.pending_subscribes.is_empty());
+        let request: serde_json::Value =
+            serde_json::from_str(sent.0[1].to_text().unwrap()).unwrap();
+        assert_eq!(request["method"], "unsubscribe");
+        assert_eq!(request["params"]["channel"], "logs:api");
+
+        lp.handle_text(&mut sent, r#"{"id":2,"result":{}}"#).await;
+
+        assert!(conn.state.subscriptions.read().await.is_empty());
+        assert!(lp.channel_to_id.is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refresh_deadline_follows_the_ttl() {
+        let before = Instant::now();
+        let deadline =
+            refresh_deadline(Some(&serde_json::json!({ "expires": true, "ttl": 60 }))).unwrap();
+        assert!(deadline >= before + Duration::from_secs(60));
+        assert!(deadline <= Instant::now() + Duration::from_secs(60));
+
+        assert_eq!(
+            refresh_deadline(Some(
+                &serde_json::json!({ "expires": true, "ttl": u64::MAX })
+            )),
+            None
+        );
+        assert_eq!(
+            refresh_deadline(Some(&serde_json::json!({ "expires": false, "ttl": 60 }))),
+            None
+        );
+        assert_eq!(refresh_deadline(None), None);
+    }
+
+    #[test]
+    fn normalizes_channel_prefixes() {
+        assert_eq!(normalize_channel_prefix("logs:"), "logs:");
+        assert_eq!(normalize_channel_prefix("team"), "team:");
+        assert_eq!(normalize_channel_prefix(" team "), "team:");
+        assert_eq!(normalize_channel_prefix(""), "");
+        assert_eq!(normalize_channel_prefix("  "), "");
+    }
+
+    #[test]
+    fn prefixes_and_strips_channel_names() {
+        assert_eq!(prefixed_channel("team:", "api"), "team:api");
+        assert_eq!(strip_channel_prefix("team:", "team:api"), Some("api"));
+        assert_eq!(strip_channel_prefix("team:", "logs:api"), None);
+
+        assert_eq!(prefixed_channel("", "api"), "api");
+        assert_eq!(strip_channel_prefix("", "api"), Some("api"));
+        assert_eq!(strip_channel_prefix("", "logs:api"), Some("logs:api"));
+    }
+
+    #[tokio::test]
+    async fn uses_a_custom_channel_prefix_and_ignores_foreign_pushes() {
+        let (conn, events) = test_connection();
+        let options = ConnectOptions {
+            channel_prefix: Some("team".to_string()),
+            ..ConnectOptions::default()
+        };
+        let mut lp = ConnectionLoop::new(
+            conn.clone(),
+            TokenSource::Static("token".to_string()),
+            &options,
+        );
+
+        let mut sent = Sent::default();
+        lp.send_subscribe(&mut sent, "a".to_string(), "api".to_string())
+            .await;
+        let request: serde_json::Value =
+            serde_json::from_str(sent.0[0].to_text().unwrap()).unwrap();
+        assert_eq!(request["params"]["channel"], "team:api");
+
+        let frame = concat!(
+            r#"{"id":2,"result":{}}"#,
+            "\n",
+            r#"{"channel":"logs:api","pub":{"data":{"msg":"foreign"}}}"#,
+            "\n",
+            r#"{"channel":"team:api","pub":{"data":{"msg":"ours"}}}"#,
+        );
+        assert_eq!(
+            lp.handle_text(&mut sent, frame).await,
+            FrameOutcome::Continue
+        );
+
+        let publications: Vec<serde_json::Value> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                CentrifugoEvent::Publication { channel_id, data } if channel_id == "a" => {
+                    Some(data.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(publications.len(), 1);
+        assert_eq!(publications[0]["msg"], "ours");
+    }
+
+    #[test]
+    fn request_ids_wrap_past_the_connect_id_and_skip_pending_ones() {
+        let mut ids = RequestIds { next: u32::MAX - 1 };
+        let pending_subscribes = HashMap::from([(u32::MAX, pending("a", "api"))]);
+        let (reply, _) = oneshot::channel();
+        let publish = PendingPublish {
+            reply,
+            sent_at: Instant::now(),
+        };
+        let pending_publishes = HashMap::from([(FIRST_REQUEST_ID, publish)]);
+        let pending_refresh = Some(FIRST_REQUEST_ID + 1);
+
+        let allocated: Vec<u32> = (0..3)
+            .map(|_| ids.next_request_id(&pending_subscribes, &pending_publishes, pending_refresh))
+            .collect();
+        assert_eq!(
+            allocated,
+            [u32::MAX - 1, FIRST_REQUEST_ID + 2, FIRST_REQUEST_ID + 3]
+        );
+        assert!(!allocated.contains(&CONNECT_REQUEST_ID));
+    }
+
+    #[test]
+    fn reconnect_delay_backs_off_with_jitter_up_to_the_cap() {
+        for _ in 0..100 {
+            let first = reconnect_delay(1);
+            assert!(first >= RECONNECT_BASE_DELAY / 2 && first <= RECONNECT_BASE_DELAY);
+            let third = reconnect_delay(3);
+            assert!(third >= RECONNECT_BASE_DELAY * 2 && third <= RECONNECT_BASE_DELAY * 4);
+            for attempt in [7, 20, u32::MAX] {
+                let capped = reconnect_delay(attempt);
+                assert!(capped >= RECONNECT_MAX_DELAY / 2 && capped <= RECONNECT_MAX_DELAY);
+            }
+        }
+    }
+
+    /// Accepts one client on the mock server and answers its connect request
+    async fn accept_client(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let request = next_request(&mut ws).await;
+        assert_eq!(request["id"], CONNECT_REQUEST_ID);
+        assert_eq!(request["method"], "connect");
+        ws.send(Message::Text(r#"{"id":1,"result":{}}"#.into()))
+            .await
+            .unwrap();
+        ws
+    }
+
+    async fn next_request(ws: &mut WebSocketStream<TcpStream>) -> serde_json::Value {
+        let message = ws.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_resubscribes_after_the_server_drops() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (conn, events) = test_connection();
+        conn.state
+            .subscriptions
+            .write()
+            .await
+            .insert("a".to_string(), "api".to_string());
+
+        let server = tokio::spawn(async move {
+            let mut first = accept_client(&listener).await;
+            next_request(&mut first).await;
+            first.close(None).await.unwrap();
+            drop(first);
+
+            let mut second = accept_client(&listener).await;
+            let resubscribe = next_request(&mut second).await;
+            (second, resubscribe)
+        });
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let client = tokio::spawn(async move {
+            let token = TokenSource::Static("token".to_string());
+            run_websocket_loop(conn, url, token, ConnectOptions::default(), &mut rx).await;
+        });
+
+        let (_second, resubscribe) = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resubscribe["method"], "subscribe");
+        assert_eq!(resubscribe["params"]["channel"], "logs:api");
+
+        tx.send(CentrifugoCommand::Disconnect).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), client)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                CentrifugoEvent::Connected,
+                CentrifugoEvent::Disconnected { .. },
+                CentrifugoEvent::Reconnecting { attempt: 1 },
+                CentrifugoEvent::Connected,
+            ]
+        ));
+    }
+}
@@ -1,7 +1,9 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod app_log;
 mod centrifugo;
+mod test_logs;
 
 
 fn main() {
@@ -10,6 +12,10 @@ fn main() {
         .setup(|app| {
             let handle = app.handle().clone();
 
+            if let Err(e) = app_log::init(&handle) {
+                eprintln!("Failed to initialize app log: {}", e);
+            }
+
             // Start Centrifugo connection manager in background
             tauri::async_runtime::spawn(async move {
                 centrifugo::start_connection_manager(handle).await;
@@ -23,6 +29,14 @@ fn main() {
             centrifugo::unsubscribe_channel,
             centrifugo::disconnect_centrifugo,
             centrifugo::get_connection_status,
+            centrifugo::get_subscriptions,
+            centrifugo::publish_centrifugo,
+            centrifugo::set_live_filter,
+            centrifugo::list_stale_subscriptions,
+            centrifugo::clear_stale_subscriptions,
+            test_logs::inject_test_logs,
+            app_log::get_app_log_path,
+            app_log::tail_app_log,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,131 @@
+//! Synthetic log generator
+//!
+//! Emits plausible log entries through the same `centrifugo-event` path as
+//! real publications, so the UI can be exercised without a Centrifugo server.
+//! The frontend stores injected entries exactly like live ones.
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+
+use crate::centrifugo::{emit_event, CentrifugoEvent, ConnectionState};
+
+/// Number of entries per emitted batch publication
+const BATCH_SIZE: usize = 100;
+
+/// Most entries a single call may generate
+const MAX_COUNT: u64 = 100_000;
+
+/// Time span the generated timestamps are spread over
+const SPREAD_MS: u64 = 60 * 60 * 1000;
+
+/// Pino levels with relative weights, so most entries are info/debug
+const LEVELS: &[(u64, &str, u64)] = &[
+    (10, "trace", 5),
+    (20, "debug", 20),
+    (30, "info", 50),
+    (40, "warn", 15),
+    (50, "error", 8),
+    (60, "fatal", 2),
+];
+
+const NAMESPACES: &[&str] = &["api", "api.auth", "db", "db.pool", "worker", "cache"];
+
+const MESSAGES: &[&str] = &[
+    "Request completed",
+    "User signed in",
+    "Cache miss",
+    "Query executed",
+    "Job enqueued",
+    "Retrying connection",
+    "Slow response detected",
+    "Connection refused",
+];
+
+/// Small xorshift generator; test data doesn't need a real RNG
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+/// Generate `count` test logs and emit them as publications for a channel
+#[tauri::command]
+pub async fn inject_test_logs(
+    app: AppHandle,
+    state: State<'_, Arc<ConnectionState>>,
+    channel_id: String,
+    count: u64,
+) -> Result<u64, String> {
+    if count > MAX_COUNT {
+        return Err(format!(
+            "Cannot inject more than {} test logs at once",
+            MAX_COUNT
+        ));
+    }
+
+    let channel = state
+        .channel_name(&channel_id)
+        .await
+        .unwrap_or_else(|| channel_id.clone());
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    let now_ms = now.as_millis() as u64;
+    let mut rng = Xorshift(now.as_nanos() as u64 | 1);
+    let total_weight: u64 = LEVELS.iter().map(|(_, _, weight)| weight).sum();
+
+    let mut entries = Vec::with_capacity(count.min(BATCH_SIZE as u64) as usize);
+    for i in 0..count {
+        let mut roll = rng.next() % total_weight;
+        let &(level, level_label, _) = LEVELS
+            .iter()
+            .find(|(_, _, weight)| {
+                let hit = roll < *weight;
+                roll = roll.saturating_sub(*weight);
+                hit
+            })
+            .unwrap_or(&LEVELS[2]);
+
+        // Oldest first, spread evenly over the last hour with some jitter
+        let offset = SPREAD_MS * (count - i) / count.max(1);
+        let time = now_ms - offset + rng.next() % 1000;
+
+        entries.push(json!({
+            "id": format!("test-{}-{}", now_ms, i),
+            "level": level,
+            "levelLabel": level_label,
+            "time": time,
+            "msg": rng.pick(MESSAGES),
+            "namespace": rng.pick(NAMESPACES),
+            "channel": channel,
+            "data": {
+                "test": true,
+                "durationMs": rng.next() % 2000,
+            },
+        }));
+
+        if entries.len() == BATCH_SIZE || i + 1 == count {
+            emit_event(
+                &app,
+                CentrifugoEvent::Publication {
+                    channel_id: channel_id.clone(),
+                    data: json!({ "type": "batch", "data": std::mem::take(&mut entries) }),
+                },
+            );
+        }
+    }
+
+    Ok(count)
+}